@@ -1,129 +1,912 @@
-use serde::Serialize;
+use rodio::source::SineWave;
+use rodio::{Decoder, OutputStream, OutputStreamHandle, Sink, Source};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
-use std::time::Duration;
-use tauri::{AppHandle, Emitter, State};
-use tokio::{
-    sync::oneshot,
-    task::JoinHandle,
-    time::{self, Instant},
-};
-
-// 定时器状态
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tauri::{AppHandle, Emitter, Manager, State};
+use tauri_plugin_notification::NotificationExt;
+use tokio::time::{self, Instant};
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+
+// 按 id 索引的计时器集合
+type TimerMap = Arc<Mutex<HashMap<String, TimerState>>>;
+// 按 id 索引的番茄钟周期集合
+type CycleMap = Arc<Mutex<HashMap<String, CycleState>>>;
+
+// 可暂停的逻辑时钟：记录跨暂停/恢复累积的真实专注时长，
+// 而不是依赖单一的墙钟 deadline
+#[derive(Clone, Copy)]
+struct LogicalClock {
+    accumulated: Duration,
+    started: Option<Instant>,
+}
+
+impl Default for LogicalClock {
+    fn default() -> Self {
+        Self { accumulated: Duration::ZERO, started: None }
+    }
+}
+
+impl LogicalClock {
+    // 创建一个从此刻开始计时的时钟
+    fn started_now() -> Self {
+        Self { accumulated: Duration::ZERO, started: Some(Instant::now()) }
+    }
+
+    // 已流逝的逻辑时长：运行中为 accumulated + (now - started)，暂停时为 accumulated
+    fn elapsed(&self) -> Duration {
+        match self.started {
+            Some(start) => self.accumulated + Instant::now().saturating_duration_since(start),
+            None => self.accumulated,
+        }
+    }
+
+    // 暂停：把当前这一段运行时长折算进 accumulated，并清空 started
+    fn pause(&mut self) {
+        if let Some(start) = self.started.take() {
+            self.accumulated += Instant::now().saturating_duration_since(start);
+        }
+    }
+
+    // 恢复：从此刻开始新的一段运行
+    fn resume(&mut self) {
+        self.started = Some(Instant::now());
+    }
+}
+
+// 单个计时器的状态
 #[derive(Default)]
 struct TimerState {
-    handle: Option<JoinHandle<()>>,
-    cancel_tx: Option<oneshot::Sender<()>>,
-    end_instant: Option<Instant>,
-    paused_remaining: Option<Duration>,
+    cancel_token: Option<CancellationToken>,
+    clock: LogicalClock,
+    total_ms: u64,
     running: bool,
     paused: bool,
 }
 
+// 内置提示音
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum AlarmSound {
+    Chime,
+    Bell,
+    Digital,
+}
+
+impl AlarmSound {
+    fn from_label(label: &str) -> Self {
+        match label {
+            "bell" => AlarmSound::Bell,
+            "digital" => AlarmSound::Digital,
+            _ => AlarmSound::Chime,
+        }
+    }
+
+    // 内置提示音没有打包音频资源，用合成音替代：(频率 Hz, 时长 ms)
+    fn tone(self) -> (f32, u64) {
+        match self {
+            AlarmSound::Chime => (880.0, 600),
+            AlarmSound::Bell => (660.0, 900),
+            AlarmSound::Digital => (1200.0, 300),
+        }
+    }
+}
+
+// 提示音配置
+struct AlarmConfig {
+    sound: AlarmSound,
+    custom_file: Option<String>,
+    volume: f32,
+}
+
+impl Default for AlarmConfig {
+    fn default() -> Self {
+        Self { sound: AlarmSound::Chime, custom_file: None, volume: 0.6 }
+    }
+}
+
+// cpal 的输出流在部分后端上有线程亲和性限制（创建它的线程销毁/移动会导致
+// 播放异常甚至 UB），不能简单靠 unsafe impl Send 把它塞进托管状态了事。
+// 做法是专门起一个永不退出的线程持有 OutputStream，只把可以安全跨线程
+// 共享的 OutputStreamHandle 通过 channel 带回来
+fn spawn_audio_thread() -> Option<OutputStreamHandle> {
+    let (tx, rx) = std::sync::mpsc::channel();
+    std::thread::spawn(move || match OutputStream::try_default() {
+        Ok((stream, handle)) => {
+            let _ = tx.send(Some(handle));
+            // 让 stream 绑定在这个专用线程上一直存活
+            loop {
+                std::thread::park();
+            }
+            #[allow(unreachable_code)]
+            {
+                drop(stream);
+            }
+        }
+        Err(e) => {
+            log::warn!("无法打开音频输出设备: {e}");
+            let _ = tx.send(None);
+        }
+    });
+    rx.recv().ok().flatten()
+}
+
+// 提示音播放状态：OutputStreamHandle 本身是 Send + Sync 的，可以直接放进
+// 托管状态；真正有线程亲和性的 OutputStream 留在专用线程里。
+// sinks 按 timer_id 分开存放——多个计时器前后脚到期时各自持有自己的 Sink，
+// 不会出现后一个 Sink 覆盖并静音前一个正在播放的提示音
+#[derive(Default)]
+struct AlarmState {
+    handle: Mutex<Option<OutputStreamHandle>>,
+    sinks: Mutex<HashMap<String, Sink>>,
+    config: Mutex<AlarmConfig>,
+}
+
+// 播放一次提示音：用户自定义文件优先，否则回退到内置合成音
+fn play_alarm(app: &AppHandle, timer_id: &str) {
+    let alarm = app.state::<AlarmState>();
+
+    let mut handle_guard = match alarm.handle.lock() {
+        Ok(g) => g,
+        Err(_) => return,
+    };
+    if handle_guard.is_none() {
+        *handle_guard = spawn_audio_thread();
+    }
+    let Some(handle) = handle_guard.clone() else { return };
+    drop(handle_guard);
+
+    let config = match alarm.config.lock() {
+        Ok(c) => c,
+        Err(_) => return,
+    };
+
+    let sink = match Sink::try_new(&handle) {
+        Ok(s) => s,
+        Err(e) => {
+            log::warn!("无法创建音频 sink: {e}");
+            return;
+        }
+    };
+    sink.set_volume(config.volume);
+
+    let Ok(mut sinks) = alarm.sinks.lock() else { return };
+
+    if let Some(path) = &config.custom_file {
+        match File::open(path).map(BufReader::new).and_then(|f| {
+            Decoder::new(f).map_err(|e| std::io::Error::other(e.to_string()))
+        }) {
+            Ok(source) => {
+                sink.append(source);
+                sinks.insert(timer_id.to_string(), sink);
+                return;
+            }
+            Err(e) => log::warn!("无法播放自定义提示音 {path}: {e}"),
+        }
+    }
+
+    let (freq, duration_ms) = config.sound.tone();
+    let source = SineWave::new(freq)
+        .take_duration(Duration::from_millis(duration_ms))
+        .amplify(0.2);
+    sink.append(source);
+    sinks.insert(timer_id.to_string(), sink);
+}
+
+// 发一条桌面通知；finished_phase 为 None 表示普通单次倒计时结束
+fn notify_done(app: &AppHandle, finished_phase: Option<Phase>) {
+    let body = match finished_phase {
+        None => "计时结束",
+        Some(Phase::Work) => "工作时段结束，去休息一下吧",
+        Some(Phase::ShortBreak) | Some(Phase::LongBreak) => "休息结束，开始专注吧",
+    };
+    let _ = app.notification().builder().title("番茄钟").body(body).show();
+}
+
 // Tick 事件载荷
 #[derive(Serialize, Clone)]
 struct TickPayload {
+    timer_id: String,
     remaining_ms: u64,
 }
 
-// 开始计时器
-#[tauri::command]
-async fn start_timer(
-    app: AppHandle,
-    state: State<'_, Arc<Mutex<TimerState>>>,
-    total_ms: u64,
-) -> Result<(), String> {
-    let mut s = state.lock().map_err(|e| e.to_string())?;
+// 完成事件载荷
+#[derive(Serialize, Clone)]
+struct DonePayload {
+    timer_id: String,
+}
 
-    // 如果已有任务，先停止
-    if let Some(tx) = s.cancel_tx.take() {
-        let _ = tx.send(());
+// 番茄钟阶段
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+enum Phase {
+    #[default]
+    Work,
+    ShortBreak,
+    LongBreak,
+}
+
+// 番茄钟周期配置：各阶段时长 + 触发长休息的工作间隔数
+struct CycleConfig {
+    work_ms: u64,
+    short_break_ms: u64,
+    long_break_ms: u64,
+    long_break_every: u32,
+}
+
+impl Default for CycleConfig {
+    fn default() -> Self {
+        Self {
+            work_ms: 25 * 60 * 1000,
+            short_break_ms: 5 * 60 * 1000,
+            long_break_ms: 15 * 60 * 1000,
+            long_break_every: 4,
+        }
     }
-    if let Some(h) = s.handle.take() {
-        h.abort();
+}
+
+// 单个计时器的番茄钟周期状态，叠加在 TimerState 之上
+#[derive(Default)]
+struct CycleState {
+    active: bool,
+    phase: Phase,
+    completed_work_intervals: u32,
+    config: CycleConfig,
+    // 本轮周期里累计的、只在 Work 阶段流逝的专注时长；
+    // 跨阶段切换保留，不随 TimerState.clock 在阶段边界被重置而清零
+    work_focus_ms: u64,
+}
+
+// 阶段切换事件载荷
+#[derive(Serialize, Clone)]
+struct PhasePayload {
+    timer_id: String,
+    phase: Phase,
+    index: u32,
+}
+
+impl CycleState {
+    // 当前阶段结束后，推进到下一阶段，返回 (新阶段, 新阶段时长)；
+    // elapsed_ms 是刚结束的这个阶段实际流逝的时长，只有 Work 阶段会计入 work_focus_ms
+    fn advance(&mut self, elapsed_ms: u64) -> (Phase, u64) {
+        match self.phase {
+            Phase::Work => {
+                self.completed_work_intervals += 1;
+                self.work_focus_ms += elapsed_ms;
+                if self.completed_work_intervals % self.config.long_break_every == 0 {
+                    self.phase = Phase::LongBreak;
+                    (Phase::LongBreak, self.config.long_break_ms)
+                } else {
+                    self.phase = Phase::ShortBreak;
+                    (Phase::ShortBreak, self.config.short_break_ms)
+                }
+            }
+            Phase::ShortBreak | Phase::LongBreak => {
+                self.phase = Phase::Work;
+                (Phase::Work, self.config.work_ms)
+            }
+        }
     }
+}
 
-    if total_ms == 0 {
-        return Ok(());
+// 取消某个 id 当前运行的任务（如果有）
+fn cancel_running(timers: &mut HashMap<String, TimerState>, timer_id: &str) {
+    if let Some(existing) = timers.get_mut(timer_id) {
+        if let Some(token) = existing.cancel_token.take() {
+            token.cancel();
+        }
     }
+}
 
-    s.running = true;
-    s.paused = false;
-    s.paused_remaining = None;
+// 根据逻辑时钟和阶段总时长算出剩余毫秒数
+fn remaining_ms_of(s: &TimerState) -> u64 {
+    let elapsed_ms = s.clock.elapsed().as_millis() as u64;
+    s.total_ms.saturating_sub(elapsed_ms)
+}
 
-    let end = Instant::now() + Duration::from_millis(total_ms);
-    s.end_instant = Some(end);
+// 持久化的周期信息（用于重启后恢复阶段/计数）
+#[derive(Serialize, Deserialize, Clone)]
+struct PersistedCycle {
+    phase: Phase,
+    completed_work_intervals: u32,
+    work_ms: u64,
+    short_break_ms: u64,
+    long_break_ms: u64,
+    long_break_every: u32,
+    work_focus_ms: u64,
+}
+
+// 暂停态下逻辑时钟是冻结的，不能再套用"墙钟 deadline"那一套——
+// 否则重启时会把关闭期间流逝的真实时间错当成这个计时器也在倒计时。
+// 所以运行中和暂停中分别持久化各自的语义：运行中存到期的墙钟时间戳，
+// 暂停中直接存冻结时刻的剩余毫秒数
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "snake_case")]
+enum PersistedTimerState {
+    Running { deadline_unix_ms: u64 },
+    Paused { remaining_ms: u64 },
+}
+
+// 持久化到磁盘的计时器快照
+#[derive(Serialize, Deserialize, Clone)]
+struct PersistedTimer {
+    state: PersistedTimerState,
+    cycle: Option<PersistedCycle>,
+}
+
+fn persist_path(app: &AppHandle) -> Result<PathBuf, String> {
+    let dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
+    Ok(dir.join("timers.json"))
+}
 
-    let (tx, mut rx) = oneshot::channel::<()>();
-    s.cancel_tx = Some(tx);
+fn load_persisted(app: &AppHandle) -> HashMap<String, PersistedTimer> {
+    let Ok(path) = persist_path(app) else { return HashMap::new() };
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default()
+}
 
-    let app_clone = app.clone();
-    let handle = tokio::spawn(async move {
+fn write_persisted(app: &AppHandle, data: &HashMap<String, PersistedTimer>) {
+    let Ok(path) = persist_path(app) else { return };
+    if let Ok(json) = serde_json::to_string(data) {
+        let _ = std::fs::write(path, json);
+    }
+}
+
+fn remove_persisted(app: &AppHandle, timer_id: &str) {
+    let mut data = load_persisted(app);
+    if data.remove(timer_id).is_some() {
+        write_persisted(app, &data);
+    }
+}
+
+fn now_unix_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
+
+// 把某个 id 当前的状态（运行中存 deadline、暂停中存剩余时长，外加周期信息）写入磁盘；
+// 若该 id 已经停止/不存在，则把它从持久化文件中移除
+fn persist_current(app: &AppHandle, timers: &TimerMap, cycles: &CycleMap, timer_id: &str) {
+    let snapshot = timers
+        .lock()
+        .ok()
+        .and_then(|t| t.get(timer_id).map(|s| (s.running, s.paused, remaining_ms_of(s))));
+    let Some((running, paused, remaining_ms)) = snapshot else {
+        remove_persisted(app, timer_id);
+        return;
+    };
+    if !running && !paused {
+        remove_persisted(app, timer_id);
+        return;
+    }
+
+    let state = if paused {
+        PersistedTimerState::Paused { remaining_ms }
+    } else {
+        PersistedTimerState::Running { deadline_unix_ms: now_unix_ms() + remaining_ms }
+    };
+
+    let cycle = cycles.lock().ok().and_then(|c| {
+        c.get(timer_id).filter(|c| c.active).map(|c| PersistedCycle {
+            phase: c.phase,
+            completed_work_intervals: c.completed_work_intervals,
+            work_ms: c.config.work_ms,
+            short_break_ms: c.config.short_break_ms,
+            long_break_ms: c.config.long_break_ms,
+            long_break_every: c.config.long_break_every,
+            work_focus_ms: c.work_focus_ms,
+        })
+    });
+
+    let mut data = load_persisted(app);
+    data.insert(timer_id.to_string(), PersistedTimer { state, cycle });
+    write_persisted(app, &data);
+}
+
+// 应用启动时从磁盘恢复计时器：暂停中的原样恢复成暂停态，不重新武装也不触发
+// 任何到期副作用；运行中的按 deadline 是否已过，要么按剩余时间重新武装，
+// 要么走一遍离线到期的完整流程
+fn restore_all(app: &AppHandle) {
+    let data = load_persisted(app);
+    if data.is_empty() {
+        return;
+    }
+
+    let timers_state = app.state::<TimerMap>().inner().clone();
+    let cycles_state = app.state::<CycleMap>().inner().clone();
+    let root_token = app.state::<CancellationToken>().inner().clone();
+    let tracker = app.state::<TaskTracker>().inner().clone();
+    let now = now_unix_ms();
+
+    for (timer_id, persisted) in data {
+        if let Some(pc) = &persisted.cycle {
+            let mut cycles = cycles_state.lock().unwrap();
+            cycles.insert(
+                timer_id.clone(),
+                CycleState {
+                    active: true,
+                    phase: pc.phase,
+                    completed_work_intervals: pc.completed_work_intervals,
+                    config: CycleConfig {
+                        work_ms: pc.work_ms,
+                        short_break_ms: pc.short_break_ms,
+                        long_break_ms: pc.long_break_ms,
+                        long_break_every: pc.long_break_every,
+                    },
+                    work_focus_ms: pc.work_focus_ms,
+                },
+            );
+        }
+
+        match persisted.state {
+            PersistedTimerState::Paused { remaining_ms } => {
+                // 暂停态的逻辑时钟本就是冻结的，恢复成同样冻结的时钟即可：
+                // 不武装倒计时任务，也绝不能触发到期的提示音/通知/阶段推进
+                let mut timers = timers_state.lock().unwrap();
+                timers.insert(
+                    timer_id.clone(),
+                    TimerState {
+                        cancel_token: None,
+                        clock: LogicalClock::default(),
+                        total_ms: remaining_ms,
+                        running: false,
+                        paused: true,
+                    },
+                );
+            }
+            PersistedTimerState::Running { deadline_unix_ms } if deadline_unix_ms > now => {
+                let remaining_ms = deadline_unix_ms - now;
+                let token = root_token.child_token();
+                {
+                    let mut timers = timers_state.lock().unwrap();
+                    timers.insert(
+                        timer_id.clone(),
+                        TimerState {
+                            cancel_token: Some(token.clone()),
+                            clock: LogicalClock::started_now(),
+                            total_ms: remaining_ms,
+                            running: true,
+                            paused: false,
+                        },
+                    );
+                }
+                spawn_tick_task(
+                    app.clone(),
+                    timers_state.clone(),
+                    cycles_state.clone(),
+                    tracker.clone(),
+                    timer_id.clone(),
+                    token,
+                );
+            }
+            PersistedTimerState::Running { .. } => {
+                // 应用关闭期间已经到期：和运行中自然到期走同一套流程——
+                // 播放提示音、发桌面通知，周期模式下还要推进到下一阶段并重新
+                // 武装倒计时，而不是让周期卡在 active:true 却没有对应的计时任务
+                let _ = app.emit("timer://done", DonePayload { timer_id: timer_id.clone() });
+                play_alarm(app, &timer_id);
+
+                let finished_phase = persisted.cycle.as_ref().map(|pc| pc.phase);
+                notify_done(app, finished_phase);
+
+                if let Some(pc) = &persisted.cycle {
+                    let elapsed_ms = match pc.phase {
+                        Phase::Work => pc.work_ms,
+                        Phase::ShortBreak => pc.short_break_ms,
+                        Phase::LongBreak => pc.long_break_ms,
+                    };
+                    let (phase, duration_ms) = {
+                        let mut cycles = cycles_state.lock().unwrap();
+                        cycles.get_mut(&timer_id).unwrap().advance(elapsed_ms)
+                    };
+                    let index = cycles_state
+                        .lock()
+                        .map(|c| c.get(&timer_id).map(|c| c.completed_work_intervals).unwrap_or(0))
+                        .unwrap_or(0);
+                    let _ = app.emit(
+                        "timer://phase",
+                        PhasePayload { timer_id: timer_id.clone(), phase, index },
+                    );
+
+                    let token = root_token.child_token();
+                    {
+                        let mut timers = timers_state.lock().unwrap();
+                        timers.insert(
+                            timer_id.clone(),
+                            TimerState {
+                                cancel_token: Some(token.clone()),
+                                clock: LogicalClock::started_now(),
+                                total_ms: duration_ms,
+                                running: true,
+                                paused: false,
+                            },
+                        );
+                    }
+                    spawn_tick_task(
+                        app.clone(),
+                        timers_state.clone(),
+                        cycles_state.clone(),
+                        tracker.clone(),
+                        timer_id.clone(),
+                        token,
+                    );
+                    persist_current(app, &timers_state, &cycles_state, &timer_id);
+                } else {
+                    remove_persisted(app, &timer_id);
+                }
+            }
+        }
+    }
+}
+
+// 启动某个 id 的倒计时任务，交给 TaskTracker 托管；
+// 到点后若该 id 处于周期模式则自动推进到下一阶段，否则照常结束
+fn spawn_tick_task(
+    app: AppHandle,
+    timers: TimerMap,
+    cycles: CycleMap,
+    tracker: TaskTracker,
+    timer_id: String,
+    token: CancellationToken,
+) {
+    tracker.spawn(async move {
+        let tick_event = format!("timer://tick/{timer_id}");
         let mut ticker = time::interval(Duration::from_millis(100)); // 每 100ms 更新一次
         ticker.set_missed_tick_behavior(time::MissedTickBehavior::Skip);
 
         loop {
             tokio::select! {
                 _ = ticker.tick() => {
-                    let now = Instant::now();
-                    if now >= end {
-                        let _ = app_clone.emit("timer://tick", TickPayload { remaining_ms: 0 });
-                        let _ = app_clone.emit("timer://done", ());
+                    let remaining_ms = match timers.lock().unwrap().get(&timer_id) {
+                        Some(s) => remaining_ms_of(s),
+                        None => break,
+                    };
+
+                    if remaining_ms == 0 {
+                        let _ = app.emit(&tick_event, TickPayload { timer_id: timer_id.clone(), remaining_ms: 0 });
+                        let _ = app.emit("timer://done", DonePayload { timer_id: timer_id.clone() });
+                        play_alarm(&app, &timer_id);
+
+                        let finished_phase = cycles
+                            .lock()
+                            .map(|c| c.get(&timer_id).filter(|c| c.active).map(|c| c.phase))
+                            .unwrap_or(None);
+                        notify_done(&app, finished_phase);
+
+                        if finished_phase.is_some() {
+                            let elapsed_ms = timers
+                                .lock()
+                                .ok()
+                                .and_then(|t| t.get(&timer_id).map(|s| s.total_ms))
+                                .unwrap_or(0);
+                            let (phase, duration_ms) = {
+                                let mut guard = cycles.lock().unwrap();
+                                guard.get_mut(&timer_id).unwrap().advance(elapsed_ms)
+                            };
+                            let index = cycles
+                                .lock()
+                                .map(|c| c.get(&timer_id).map(|c| c.completed_work_intervals).unwrap_or(0))
+                                .unwrap_or(0);
+                            let _ = app.emit(
+                                "timer://phase",
+                                PhasePayload { timer_id: timer_id.clone(), phase, index },
+                            );
+
+                            if let Ok(mut t) = timers.lock() {
+                                if let Some(s) = t.get_mut(&timer_id) {
+                                    s.total_ms = duration_ms;
+                                    s.clock = LogicalClock::started_now();
+                                }
+                            }
+                            persist_current(&app, &timers, &cycles, &timer_id);
+                            continue;
+                        }
+
+                        if let Ok(mut t) = timers.lock() {
+                            if let Some(s) = t.get_mut(&timer_id) {
+                                s.running = false;
+                                // 倒计时已结束，冻结逻辑时钟，之后不再计入专注时长
+                                s.clock.pause();
+                            }
+                        }
+                        remove_persisted(&app, &timer_id);
                         break;
                     } else {
-                        let remaining = end.saturating_duration_since(now);
-                        let remaining_ms = remaining.as_millis() as u64;
-                        let _ = app_clone.emit("timer://tick", TickPayload { remaining_ms });
+                        let _ = app.emit(&tick_event, TickPayload { timer_id: timer_id.clone(), remaining_ms });
                     }
                 }
-                _ = &mut rx => {
-                    // 收到取消信号
-                    let now = Instant::now();
-                    if now < end {
-                        let remaining = end.saturating_duration_since(now);
-                        // 发送当前剩余时间
-                        let remaining_ms = remaining.as_millis() as u64;
-                        let _ = app_clone.emit("timer://tick", TickPayload { remaining_ms });
+                _ = token.cancelled() => {
+                    // 收到取消信号，发送当前剩余时间
+                    if let Some(s) = timers.lock().unwrap().get(&timer_id) {
+                        let remaining_ms = remaining_ms_of(s);
+                        let _ = app.emit(&tick_event, TickPayload { timer_id: timer_id.clone(), remaining_ms });
                     }
                     break;
                 }
             }
         }
     });
+}
 
-    s.handle = Some(handle);
+// 开始计时器
+#[tauri::command]
+async fn start_timer(
+    app: AppHandle,
+    state: State<'_, TimerMap>,
+    cycle: State<'_, CycleMap>,
+    root_token: State<'_, CancellationToken>,
+    tracker: State<'_, TaskTracker>,
+    timer_id: String,
+    total_ms: u64,
+) -> Result<(), String> {
+    let mut timers = state.lock().map_err(|e| e.to_string())?;
+
+    // 如果该 id 已有任务，先停止
+    cancel_running(&mut timers, &timer_id);
+
+    if total_ms == 0 {
+        drop(timers);
+        remove_persisted(&app, &timer_id);
+        return Ok(());
+    }
+
+    // 普通单次倒计时不驱动周期引擎
+    if let Ok(mut cycles) = cycle.lock() {
+        if let Some(c) = cycles.get_mut(&timer_id) {
+            c.active = false;
+        }
+    }
+
+    let token = root_token.child_token();
+
+    timers.insert(
+        timer_id.clone(),
+        TimerState {
+            cancel_token: Some(token.clone()),
+            clock: LogicalClock::started_now(),
+            total_ms,
+            running: true,
+            paused: false,
+        },
+    );
+    drop(timers);
+
+    spawn_tick_task(
+        app.clone(),
+        state.inner().clone(),
+        cycle.inner().clone(),
+        tracker.inner().clone(),
+        timer_id.clone(),
+        token,
+    );
+    persist_current(&app, state.inner(), cycle.inner(), &timer_id);
 
     Ok(())
 }
 
-// 暂停计时器
+// 启动某个 id 的一轮完整番茄钟周期：N 个工作间隔，每个之后接一次短休息，
+// 每第 long_break_every 个工作间隔后接一次长休息
 #[tauri::command]
-async fn pause_timer(state: State<'_, Arc<Mutex<TimerState>>>) -> Result<(), String> {
-    let mut s = state.lock().map_err(|e| e.to_string())?;
+async fn start_cycle(
+    app: AppHandle,
+    state: State<'_, TimerMap>,
+    cycle: State<'_, CycleMap>,
+    root_token: State<'_, CancellationToken>,
+    tracker: State<'_, TaskTracker>,
+    timer_id: String,
+    work_minutes: Option<u64>,
+    short_break_minutes: Option<u64>,
+    long_break_minutes: Option<u64>,
+    long_break_every: Option<u32>,
+) -> Result<(), String> {
+    if long_break_every == Some(0) {
+        return Err("long_break_every 必须大于等于 1".to_string());
+    }
 
-    if !s.running || s.paused {
-        return Ok(());
+    let defaults = CycleConfig::default();
+    let config = CycleConfig {
+        work_ms: work_minutes.map(|m| m * 60 * 1000).unwrap_or(defaults.work_ms),
+        short_break_ms: short_break_minutes
+            .map(|m| m * 60 * 1000)
+            .unwrap_or(defaults.short_break_ms),
+        long_break_ms: long_break_minutes
+            .map(|m| m * 60 * 1000)
+            .unwrap_or(defaults.long_break_ms),
+        // 为 0 会在 CycleState::advance 里触发除零 panic，并连带毒化
+        // 整个应用共享的 CycleMap 互斥锁，因此在进入周期配置前必须拒绝
+        long_break_every: long_break_every.unwrap_or(defaults.long_break_every),
+    };
+    let work_ms = config.work_ms;
+
+    {
+        let mut cycles = cycle.lock().map_err(|e| e.to_string())?;
+        cycles.insert(
+            timer_id.clone(),
+            CycleState {
+                active: true,
+                phase: Phase::Work,
+                completed_work_intervals: 0,
+                config,
+                work_focus_ms: 0,
+            },
+        );
     }
+    let _ = app.emit(
+        "timer://phase",
+        PhasePayload { timer_id: timer_id.clone(), phase: Phase::Work, index: 0 },
+    );
 
-    // 保存剩余时间
-    if let Some(end) = s.end_instant {
-        let now = Instant::now();
-        if now < end {
-            s.paused_remaining = Some(end.saturating_duration_since(now));
-        } else {
-            s.paused_remaining = Some(Duration::from_secs(0));
-        }
+    let mut timers = state.lock().map_err(|e| e.to_string())?;
+    cancel_running(&mut timers, &timer_id);
+
+    let token = root_token.child_token();
+
+    timers.insert(
+        timer_id.clone(),
+        TimerState {
+            cancel_token: Some(token.clone()),
+            clock: LogicalClock::started_now(),
+            total_ms: work_ms,
+            running: true,
+            paused: false,
+        },
+    );
+    drop(timers);
+
+    spawn_tick_task(
+        app.clone(),
+        state.inner().clone(),
+        cycle.inner().clone(),
+        tracker.inner().clone(),
+        timer_id.clone(),
+        token,
+    );
+    persist_current(&app, state.inner(), cycle.inner(), &timer_id);
+
+    Ok(())
+}
+
+// 跳过某个 id 当前的阶段，直接进入下一阶段（仅在周期模式下有效）
+#[tauri::command]
+async fn skip_phase(
+    app: AppHandle,
+    state: State<'_, TimerMap>,
+    cycle: State<'_, CycleMap>,
+    root_token: State<'_, CancellationToken>,
+    tracker: State<'_, TaskTracker>,
+    timer_id: String,
+) -> Result<(), String> {
+    let is_cycle_active = cycle
+        .lock()
+        .map_err(|e| e.to_string())?
+        .get(&timer_id)
+        .map(|c| c.active)
+        .unwrap_or(false);
+    if !is_cycle_active {
+        return Ok(());
     }
 
-    // 取消当前任务
-    if let Some(tx) = s.cancel_tx.take() {
-        let _ = tx.send(());
+    let mut timers = state.lock().map_err(|e| e.to_string())?;
+    // 跳过阶段时只有实际流逝的部分算专注时长，而非整个阶段的计划时长
+    let elapsed_ms = timers
+        .get(&timer_id)
+        .map(|s| s.total_ms.saturating_sub(remaining_ms_of(s)))
+        .unwrap_or(0);
+    cancel_running(&mut timers, &timer_id);
+
+    let (phase, duration_ms) = {
+        let mut cycles = cycle.lock().map_err(|e| e.to_string())?;
+        cycles
+            .get_mut(&timer_id)
+            .ok_or("unknown timer_id")?
+            .advance(elapsed_ms)
+    };
+    let index = cycle
+        .lock()
+        .map_err(|e| e.to_string())?
+        .get(&timer_id)
+        .map(|c| c.completed_work_intervals)
+        .unwrap_or(0);
+    let _ = app.emit(
+        "timer://phase",
+        PhasePayload { timer_id: timer_id.clone(), phase, index },
+    );
+
+    let token = root_token.child_token();
+
+    timers.insert(
+        timer_id.clone(),
+        TimerState {
+            cancel_token: Some(token.clone()),
+            clock: LogicalClock::started_now(),
+            total_ms: duration_ms,
+            running: true,
+            paused: false,
+        },
+    );
+    drop(timers);
+
+    spawn_tick_task(
+        app.clone(),
+        state.inner().clone(),
+        cycle.inner().clone(),
+        tracker.inner().clone(),
+        timer_id.clone(),
+        token,
+    );
+    persist_current(&app, state.inner(), cycle.inner(), &timer_id);
+
+    Ok(())
+}
+
+// 重置某个 id 的整个周期：停止计时并把阶段计数归零
+#[tauri::command]
+async fn reset_cycle(
+    app: AppHandle,
+    state: State<'_, TimerMap>,
+    cycle: State<'_, CycleMap>,
+    timer_id: String,
+) -> Result<(), String> {
+    let mut timers = state.lock().map_err(|e| e.to_string())?;
+    cancel_running(&mut timers, &timer_id);
+    if let Some(s) = timers.get_mut(&timer_id) {
+        s.running = false;
+        s.paused = false;
+        s.clock = LogicalClock::default();
+        s.total_ms = 0;
     }
-    if let Some(h) = s.handle.take() {
-        h.abort();
+
+    if let Some(c) = cycle.lock().map_err(|e| e.to_string())?.get_mut(&timer_id) {
+        c.active = false;
+        c.phase = Phase::Work;
+        c.completed_work_intervals = 0;
+        c.work_focus_ms = 0;
     }
 
-    s.running = false;
-    s.paused = true;
+    remove_persisted(&app, &timer_id);
+
+    Ok(())
+}
+
+// 暂停计时器
+#[tauri::command]
+async fn pause_timer(
+    app: AppHandle,
+    state: State<'_, TimerMap>,
+    cycle: State<'_, CycleMap>,
+    timer_id: String,
+) -> Result<(), String> {
+    {
+        let mut timers = state.lock().map_err(|e| e.to_string())?;
+        let Some(s) = timers.get_mut(&timer_id) else {
+            return Ok(());
+        };
+
+        if !s.running || s.paused {
+            return Ok(());
+        }
+
+        // 冻结逻辑时钟
+        s.clock.pause();
+
+        // 取消当前任务
+        if let Some(token) = s.cancel_token.take() {
+            token.cancel();
+        }
+
+        s.running = false;
+        s.paused = true;
+    }
+    persist_current(&app, state.inner(), cycle.inner(), &timer_id);
 
     Ok(())
 }
@@ -132,16 +915,22 @@ async fn pause_timer(state: State<'_, Arc<Mutex<TimerState>>>) -> Result<(), Str
 #[tauri::command]
 async fn resume_timer(
     app: AppHandle,
-    state: State<'_, Arc<Mutex<TimerState>>>,
+    state: State<'_, TimerMap>,
+    cycle: State<'_, CycleMap>,
+    root_token: State<'_, CancellationToken>,
+    tracker: State<'_, TaskTracker>,
+    timer_id: String,
 ) -> Result<(), String> {
-    let mut s = state.lock().map_err(|e| e.to_string())?;
+    let mut timers = state.lock().map_err(|e| e.to_string())?;
+    let Some(s) = timers.get_mut(&timer_id) else {
+        return Ok(());
+    };
 
     if !s.paused {
         return Ok(());
     }
 
-    let remain = s.paused_remaining.take().unwrap_or(Duration::from_secs(0));
-    if remain.is_zero() {
+    if remaining_ms_of(s) == 0 {
         s.running = false;
         s.paused = false;
         return Ok(());
@@ -149,67 +938,49 @@ async fn resume_timer(
 
     s.running = true;
     s.paused = false;
+    s.clock.resume();
 
-    let end = Instant::now() + remain;
-    s.end_instant = Some(end);
-
-    let (tx, mut rx) = oneshot::channel::<()>();
-    s.cancel_tx = Some(tx);
+    let token = root_token.child_token();
+    s.cancel_token = Some(token.clone());
+    drop(timers);
 
-    let app_clone = app.clone();
-    let handle = tokio::spawn(async move {
-        let mut ticker = time::interval(Duration::from_millis(100));
-        ticker.set_missed_tick_behavior(time::MissedTickBehavior::Skip);
-
-        loop {
-            tokio::select! {
-                _ = ticker.tick() => {
-                    let now = Instant::now();
-                    if now >= end {
-                        let _ = app_clone.emit("timer://tick", TickPayload { remaining_ms: 0 });
-                        let _ = app_clone.emit("timer://done", ());
-                        break;
-                    } else {
-                        let remaining = end.saturating_duration_since(now);
-                        let remaining_ms = remaining.as_millis() as u64;
-                        let _ = app_clone.emit("timer://tick", TickPayload { remaining_ms });
-                    }
-                }
-                _ = &mut rx => {
-                    let now = Instant::now();
-                    if now < end {
-                        let remaining = end.saturating_duration_since(now);
-                        let remaining_ms = remaining.as_millis() as u64;
-                        let _ = app_clone.emit("timer://tick", TickPayload { remaining_ms });
-                    }
-                    break;
-                }
-            }
-        }
-    });
-
-    s.handle = Some(handle);
+    spawn_tick_task(
+        app.clone(),
+        state.inner().clone(),
+        cycle.inner().clone(),
+        tracker.inner().clone(),
+        timer_id.clone(),
+        token,
+    );
+    persist_current(&app, state.inner(), cycle.inner(), &timer_id);
 
     Ok(())
 }
 
 // 停止计时器
 #[tauri::command]
-async fn stop_timer(state: State<'_, Arc<Mutex<TimerState>>>) -> Result<(), String> {
-    let mut s = state.lock().map_err(|e| e.to_string())?;
-
-    // 取消当前任务
-    if let Some(tx) = s.cancel_tx.take() {
-        let _ = tx.send(());
+async fn stop_timer(
+    app: AppHandle,
+    state: State<'_, TimerMap>,
+    cycle: State<'_, CycleMap>,
+    timer_id: String,
+) -> Result<(), String> {
+    let mut timers = state.lock().map_err(|e| e.to_string())?;
+    cancel_running(&mut timers, &timer_id);
+    if let Some(s) = timers.get_mut(&timer_id) {
+        s.running = false;
+        s.paused = false;
+        s.clock = LogicalClock::default();
+        s.total_ms = 0;
     }
-    if let Some(h) = s.handle.take() {
-        h.abort();
+
+    if let Ok(mut cycles) = cycle.lock() {
+        if let Some(c) = cycles.get_mut(&timer_id) {
+            c.active = false;
+        }
     }
 
-    s.running = false;
-    s.paused = false;
-    s.end_instant = None;
-    s.paused_remaining = None;
+    remove_persisted(&app, &timer_id);
 
     Ok(())
 }
@@ -217,31 +988,103 @@ async fn stop_timer(state: State<'_, Arc<Mutex<TimerState>>>) -> Result<(), Stri
 // 获取当前剩余时间
 #[tauri::command]
 async fn get_timer_remaining(
-    state: State<'_, Arc<Mutex<TimerState>>>,
+    state: State<'_, TimerMap>,
+    timer_id: String,
 ) -> Result<Option<u64>, String> {
-    let s = state.lock().map_err(|e| e.to_string())?;
-
-    if s.running && !s.paused {
-        // 如果正在运行，计算剩余时间
-        if let Some(end) = s.end_instant {
-            let now = Instant::now();
-            if now >= end {
-                return Ok(Some(0));
-            } else {
-                let remaining = end.saturating_duration_since(now);
-                return Ok(Some(remaining.as_millis() as u64));
-            }
-        }
-    } else if s.paused {
-        // 如果暂停，返回暂停时的剩余时间
-        if let Some(remaining) = s.paused_remaining {
-            return Ok(Some(remaining.as_millis() as u64));
-        }
+    let timers = state.lock().map_err(|e| e.to_string())?;
+    let Some(s) = timers.get(&timer_id) else {
+        return Ok(None);
+    };
+
+    if s.running || s.paused {
+        return Ok(Some(remaining_ms_of(s)));
     }
 
     Ok(None)
 }
 
+// 获取当前会话已累积的逻辑专注时长（毫秒），用于诚实的会话统计；
+// 周期模式下只统计 Work 阶段，休息阶段不算作专注时间
+#[tauri::command]
+async fn get_focus_elapsed(
+    state: State<'_, TimerMap>,
+    cycle: State<'_, CycleMap>,
+    timer_id: String,
+) -> Result<u64, String> {
+    let cycles = cycle.lock().map_err(|e| e.to_string())?;
+    let active_cycle = cycles.get(&timer_id).filter(|c| c.active);
+
+    if let Some(c) = active_cycle {
+        let mut focus_ms = c.work_focus_ms;
+        if c.phase == Phase::Work {
+            let timers = state.lock().map_err(|e| e.to_string())?;
+            focus_ms += timers
+                .get(&timer_id)
+                .map(|s| s.clock.elapsed().as_millis() as u64)
+                .unwrap_or(0);
+        }
+        return Ok(focus_ms);
+    }
+
+    // 非周期模式下的普通倒计时没有阶段概念，全部流逝时间都算专注时间
+    let timers = state.lock().map_err(|e| e.to_string())?;
+    Ok(timers
+        .get(&timer_id)
+        .map(|s| s.clock.elapsed().as_millis() as u64)
+        .unwrap_or(0))
+}
+
+// 重启后恢复出的计时器信息，供前端在启动时同步 UI
+#[derive(Serialize, Clone)]
+struct RestoredTimer {
+    remaining_ms: u64,
+    phase: Option<Phase>,
+}
+
+// 供前端在启动时调用，取回 setup 阶段已经从磁盘恢复好的计时器状态
+#[tauri::command]
+async fn restore_timer(
+    state: State<'_, TimerMap>,
+    cycle: State<'_, CycleMap>,
+    timer_id: String,
+) -> Result<Option<RestoredTimer>, String> {
+    let timers = state.lock().map_err(|e| e.to_string())?;
+    let Some(s) = timers.get(&timer_id) else {
+        return Ok(None);
+    };
+    if !s.running && !s.paused {
+        return Ok(None);
+    }
+
+    let phase = cycle
+        .lock()
+        .map_err(|e| e.to_string())?
+        .get(&timer_id)
+        .filter(|c| c.active)
+        .map(|c| c.phase);
+
+    Ok(Some(RestoredTimer { remaining_ms: remaining_ms_of(s), phase }))
+}
+
+// 设置提示音：内置音色、自定义文件路径（传 None 清除）、音量
+#[tauri::command]
+async fn set_alarm_sound(
+    alarm: State<'_, AlarmState>,
+    sound: Option<String>,
+    file_path: Option<String>,
+    volume: Option<f32>,
+) -> Result<(), String> {
+    let mut config = alarm.config.lock().map_err(|e| e.to_string())?;
+    if let Some(sound) = sound {
+        config.sound = AlarmSound::from_label(&sound);
+    }
+    config.custom_file = file_path;
+    if let Some(v) = volume {
+        config.volume = v.clamp(0.0, 1.0);
+    }
+    Ok(())
+}
+
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
     tauri::Builder::default()
@@ -253,16 +1096,41 @@ pub fn run() {
                         .build(),
                 )?;
             }
+            restore_all(&app.handle().clone());
             Ok(())
         })
-        .manage(Arc::new(Mutex::new(TimerState::default())))
+        .plugin(tauri_plugin_notification::init())
+        .manage(Arc::new(Mutex::new(HashMap::<String, TimerState>::new())))
+        .manage(Arc::new(Mutex::new(HashMap::<String, CycleState>::new())))
+        .manage(CancellationToken::new())
+        .manage(TaskTracker::new())
+        .manage(AlarmState::default())
         .invoke_handler(tauri::generate_handler![
             start_timer,
+            start_cycle,
+            skip_phase,
+            reset_cycle,
             pause_timer,
             resume_timer,
             stop_timer,
-            get_timer_remaining
+            get_timer_remaining,
+            get_focus_elapsed,
+            set_alarm_sound,
+            restore_timer
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running tauri application");
+        .build(tauri::generate_context!())
+        .expect("error while building tauri application")
+        .run(|app_handle, event| {
+            // 退出时取消所有计时任务，并等待它们优雅收尾，而不是粗暴 abort
+            if let tauri::RunEvent::Exit = event {
+                let root_token = app_handle.state::<CancellationToken>();
+                root_token.cancel();
+
+                let tracker = app_handle.state::<TaskTracker>().inner().clone();
+                tauri::async_runtime::block_on(async move {
+                    tracker.close();
+                    tracker.wait().await;
+                });
+            }
+        });
 }